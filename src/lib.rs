@@ -1,15 +1,18 @@
 use std::{
     collections::{HashMap, VecDeque},
-    fmt,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
+    fmt, fs,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use strum::EnumProperty;
-use strum_macros;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
 
-#[derive(Debug, PartialEq, Eq, Hash, strum_macros::EnumString, strum_macros::IntoStaticStr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum_macros::EnumString, strum_macros::IntoStaticStr)]
 pub enum Method {
     GET,
     POST,
@@ -23,6 +26,7 @@ pub enum Method {
 
 #[derive(strum_macros::EnumProperty, Debug)]
 #[allow(dead_code)]
+#[allow(non_camel_case_types)]
 pub enum Response {
     #[strum(props(content_type = "text/html"))]
     HTML(String),
@@ -57,7 +61,170 @@ pub enum Response {
     #[strum(props(content_type = "image/webp"))]
     WEBP(Vec<u8>),
 
+    // Content-Type is resolved from the file's extension at write time, so
+    // there is no static `content_type` prop to attach here.
+    FILE(PathBuf),
+
+    // Used for responses that carry no body but do carry headers, e.g. CORS
+    // preflight answers. Keyed by header name.
+    NO_CONTENT(HashMap<String, String>),
+
     ERROR(WebError),
+
+    /// Any other `Response`, with its status code, headers and cookies
+    /// overridden. Built via [`Response::builder`]/[`ResponseBuilder`]; never
+    /// constructed directly.
+    CUSTOM(Box<Response>, ResponseOverrides),
+}
+
+/// The status/headers/cookies overrides carried by a [`Response::CUSTOM`],
+/// assembled by a [`ResponseBuilder`].
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct ResponseOverrides {
+    status: Option<u16>,
+    headers: HashMap<String, String>,
+    cookies: Vec<Cookie>,
+}
+
+impl Response {
+    /// Wraps `self` in a [`ResponseBuilder`] to override its status code,
+    /// add/remove response headers, or attach cookies.
+    pub fn builder(self) -> ResponseBuilder {
+        ResponseBuilder::new(self)
+    }
+}
+
+/// Lets a handler override a [`Response`]'s status code, insert or remove
+/// response headers, and attach `Set-Cookie` headers, without hand-writing
+/// any of that framing itself. Built from [`Response::builder`], consumed by
+/// [`ResponseBuilder::build`] back into a `Response`.
+pub struct ResponseBuilder {
+    inner: Response,
+    status: Option<u16>,
+    headers: HashMap<String, String>,
+    cookies: Vec<Cookie>,
+}
+
+impl ResponseBuilder {
+    /// Wraps `response`. If it's already a [`Response::CUSTOM`] (e.g. a
+    /// middleware's `after` re-wrapping a handler's own `builder()` output),
+    /// its overrides are reused as the starting point instead of nesting
+    /// another layer of wrapping.
+    fn new(response: Response) -> Self {
+        match response {
+            Response::CUSTOM(inner, overrides) => Self {
+                inner: *inner,
+                status: overrides.status,
+                headers: overrides.headers,
+                cookies: overrides.cookies,
+            },
+            other => Self {
+                inner: other,
+                status: None,
+                headers: HashMap::new(),
+                cookies: Vec::new(),
+            },
+        }
+    }
+
+    /// Overrides the response's status code, e.g. `201` or `301`.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Inserts (or overwrites) a response header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Removes a previously inserted response header.
+    pub fn remove_header(mut self, name: &str) -> Self {
+        self.headers.remove(name);
+        self
+    }
+
+    /// Attaches a `Set-Cookie` header for `cookie`.
+    pub fn cookie(mut self, cookie: Cookie) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
+
+    pub fn build(self) -> Response {
+        Response::CUSTOM(
+            Box::new(self.inner),
+            ResponseOverrides {
+                status: self.status,
+                headers: self.headers,
+                cookies: self.cookies,
+            },
+        )
+    }
+}
+
+/// A `Set-Cookie` header, built with the usual `Path`/`HttpOnly`/`Max-Age`/
+/// `SameSite` attributes and attached to a response via
+/// [`ResponseBuilder::cookie`].
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    max_age: Option<u64>,
+    same_site: Option<String>,
+    http_only: bool,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            max_age: None,
+            same_site: None,
+            http_only: false,
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: u64) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn same_site(mut self, same_site: impl Into<String>) -> Self {
+        self.same_site = Some(same_site.into());
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    fn to_header_value(&self) -> String {
+        let mut value = format!("{}={}", self.name, self.value);
+        if let Some(path) = &self.path {
+            value.push_str(&format!("; Path={}", path));
+        }
+        if let Some(max_age) = self.max_age {
+            value.push_str(&format!("; Max-Age={}", max_age));
+        }
+        if let Some(same_site) = &self.same_site {
+            value.push_str(&format!("; SameSite={}", same_site));
+        }
+        if self.http_only {
+            value.push_str("; HttpOnly");
+        }
+        value
+    }
 }
 
 type Callback<T> = dyn Fn(Request<T>) -> Response + Send + Sync + 'static;
@@ -77,55 +244,352 @@ pub enum WebError {
     INTERNAL_SERVER_ERROR = 500,
 }
 
+impl WebError {
+    fn status_code(&self) -> u16 {
+        match self {
+            WebError::BAD_REQUEST => 400,
+            WebError::NOT_FOUND => 404,
+            WebError::INTERNAL_SERVER_ERROR => 500,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            WebError::BAD_REQUEST => "400 Bad Request",
+            WebError::NOT_FOUND => "404 Not Found",
+            WebError::INTERNAL_SERVER_ERROR => "500 Internal Server Error",
+        }
+    }
+}
+
+/// The reason phrase for the status codes this crate actually produces.
+/// Unrecognized codes (e.g. ones a handler picked via [`ResponseBuilder::status`])
+/// fall back to an empty phrase, which is legal in an HTTP status line.
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// Parses a `Cookie` request header (`"a=1; b=2"`) into a name/value map.
+fn parse_cookies(header: &str) -> HashMap<String, String> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let mut parts = pair.trim().splitn(2, '=');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if name.is_empty() {
+                None
+            } else {
+                Some((name.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// A small bundled extension -> content-type table covering the file types
+/// web apps serve most often. Users with more exotic assets can override or
+/// extend it via [`WebService::mime_types_file`].
+fn default_mime_types() -> HashMap<String, String> {
+    let table: &[(&str, &str)] = &[
+        ("html", "text/html"),
+        ("htm", "text/html"),
+        ("css", "text/css"),
+        ("js", "application/javascript"),
+        ("mjs", "application/javascript"),
+        ("json", "application/json"),
+        ("xml", "text/xml"),
+        ("svg", "image/svg+xml"),
+        ("txt", "text/plain"),
+        ("png", "image/png"),
+        ("jpg", "image/jpeg"),
+        ("jpeg", "image/jpeg"),
+        ("gif", "image/gif"),
+        ("webp", "image/webp"),
+        ("ico", "image/x-icon"),
+        ("pdf", "application/pdf"),
+        ("wasm", "application/wasm"),
+        ("woff", "font/woff"),
+        ("woff2", "font/woff2"),
+        ("ttf", "font/ttf"),
+        ("mp4", "video/mp4"),
+        ("mp3", "audio/mpeg"),
+        ("wav", "audio/wav"),
+        ("zip", "application/zip"),
+    ];
+
+    table
+        .iter()
+        .map(|(ext, content_type)| (ext.to_string(), content_type.to_string()))
+        .collect()
+}
+
+/// Parses an `/etc/mime.types`-style file, where each non-comment line is
+/// `type ext1 ext2 ...`, into an extension -> content-type map.
+fn parse_mime_types_file(contents: &str) -> HashMap<String, String> {
+    let mut table = HashMap::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let content_type = match tokens.next() {
+            Some(content_type) => content_type,
+            None => continue,
+        };
+
+        for ext in tokens {
+            table.insert(ext.to_string(), content_type.to_string());
+        }
+    }
+
+    table
+}
+
+fn mime_type_for_path<'a>(mime_types: &'a HashMap<String, String>, path: &Path) -> &'a str {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| mime_types.get(&ext.to_lowercase()))
+        .map(String::as_str)
+        .unwrap_or("application/octet-stream")
+}
+
+/// Days since the civil epoch (1970-01-01) for the given year/month/day, per
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of [`days_from_civil`]: turns a day count since 1970-01-01 back
+/// into a (year, month, day) triple.
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+/// Formats a unix timestamp as an RFC 7231 `HTTP-date`, e.g.
+/// `"Sun, 06 Nov 1994 08:49:37 GMT"`.
+fn http_date(unix_secs: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTHS: [&str; 13] = [
+        "", "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let days = (unix_secs / 86400) as i64;
+    let secs_of_day = unix_secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    let weekday = (((days % 7) + 10) % 7) as usize;
+
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[weekday],
+        day,
+        MONTHS[month as usize],
+        year,
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an RFC 7231 `HTTP-date` (the `IMF-fixdate` form used by
+/// `Last-Modified`/`If-Modified-Since`) back into a unix timestamp.
+fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    // "Sun, 06 Nov 1994 08:49:37 GMT"
+    let rest = value.split_once(", ").map(|(_, rest)| rest).unwrap_or(value);
+    let mut parts = rest.split_whitespace();
+
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time_parts = parts.next()?.split(':');
+    let hour: i64 = time_parts.next()?.parse().ok()?;
+    let minute: i64 = time_parts.next()?.parse().ok()?;
+    let second: i64 = time_parts.next()?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    if secs < 0 {
+        None
+    } else {
+        Some(secs as u64)
+    }
+}
+
+/// One node of a [`CallbackPathManager`]'s per-method route tree. A segment
+/// of a registered pattern is either a literal (stored in `static_children`),
+/// a single-segment param `{name}` (`param_child`), or a catch-all `{*name}`
+/// that binds the rest of the path and can therefore only ever be a leaf
+/// (`catch_all`). Only one param name is tracked per node, so every route
+/// registered through a given prefix must agree on the param name at that
+/// position (see [`CallbackPathManager::register`]).
+struct RouteNode<T: 'static> {
+    handler: Option<&'static Callback<T>>,
+    static_children: HashMap<String, RouteNode<T>>,
+    param_child: Option<(String, Box<RouteNode<T>>)>,
+    catch_all: Option<(String, &'static Callback<T>)>,
+}
+
+/// A matched handler plus the `{param}`/`{*catch_all}` captures gathered on
+/// the way to it, in leaf-to-root order (collected into a map by [`CallbackPathManager::find`]).
+type RouteMatch<T> = (&'static Callback<T>, Vec<(String, String)>);
+
+// Written by hand rather than derived: `#[derive(Clone)]`/`#[derive(Default)]`
+// would add a `T: Clone`/`T: Default` bound, but every field here is cheaply
+// cloneable/constructible regardless of whether `T` is (handlers are
+// `'static` function pointers).
+impl<T: 'static> Clone for RouteNode<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler,
+            static_children: self.static_children.clone(),
+            param_child: self.param_child.clone(),
+            catch_all: self.catch_all.clone(),
+        }
+    }
+}
+
+impl<T: 'static> Default for RouteNode<T> {
+    fn default() -> Self {
+        Self {
+            handler: None,
+            static_children: HashMap::new(),
+            param_child: None,
+            catch_all: None,
+        }
+    }
+}
+
 pub struct CallbackPathManager<T: 'static> {
-    handlers: Vec<Vec<(String, &'static Callback<T>)>>,
+    routes: Vec<RouteNode<T>>,
+}
+
+impl<T: 'static> Clone for CallbackPathManager<T> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+impl<T: 'static> Default for CallbackPathManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: 'static> CallbackPathManager<T> {
     pub fn new() -> Self {
         Self {
-            handlers: (0..Method::TRACE as usize).map(|_| Vec::new()).collect(),
+            routes: (0..Method::TRACE as usize)
+                .map(|_| RouteNode::default())
+                .collect(),
         }
     }
 
+    /// # Panics
+    ///
+    /// A tree node only tracks one param name at a time, so if two routes
+    /// share a prefix but disagree on the param name at the same position
+    /// (e.g. `/users/{user_id}` and `/users/{id}/posts`), the second
+    /// registration would otherwise silently bind under the first route's
+    /// name — any handler reading `req.params["id"]` would then panic at
+    /// request time instead of at startup. Panic here, at registration time,
+    /// where it's immediately traceable to the conflicting `register` calls.
     fn register(&mut self, method: Method, pattern: &str, handler: &'static Callback<T>) {
-        self.handlers[method as usize].push((pattern.to_string(), handler));
-    }
-
-    fn extract(path: &str, pattern: &str) -> HashMap<String, String> {
-        let path_tokens = path.split("/").collect::<Vec<_>>();
-        let pattern_tokens = pattern.split("/").collect::<Vec<_>>();
-        let mut params = HashMap::new();
-        for (path_token, pattern_token) in path_tokens.into_iter().zip(pattern_tokens) {
-            let wildcard = pattern_token.starts_with("{") && pattern_token.ends_with("}");
-            if wildcard {
-                let name = pattern_token
-                    .strip_prefix("{")
-                    .unwrap()
-                    .strip_suffix("}")
-                    .unwrap();
-                params.insert(name.to_string(), path_token.to_string());
+        let mut node = &mut self.routes[method as usize];
+
+        for segment in pattern.split('/').filter(|s| !s.is_empty()) {
+            if let Some(name) = segment.strip_prefix("{*").and_then(|s| s.strip_suffix('}')) {
+                node.catch_all = Some((name.to_string(), handler));
+                return;
+            }
+
+            if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                match &node.param_child {
+                    Some((existing, _)) if existing != name => panic!(
+                        "route conflict registering `{pattern}`: param `{{{name}}}` \
+                         doesn't match `{{{existing}}}` already registered for another \
+                         route sharing this prefix; param names must agree across routes \
+                         that share a prefix"
+                    ),
+                    Some(_) => {}
+                    None => {
+                        node.param_child = Some((name.to_string(), Box::new(RouteNode::default())));
+                    }
+                }
+                node = &mut node.param_child.as_mut().unwrap().1;
+            } else {
+                node = node.static_children.entry(segment.to_string()).or_default();
             }
         }
-        params
+
+        node.handler = Some(handler);
     }
 
-    fn compare(path: &str, pattern: &str) -> bool {
-        let path_tokens = path.split("/").collect::<Vec<_>>();
-        let pattern_tokens = pattern.split("/").collect::<Vec<_>>();
+    /// Walks the tree segment-by-segment, preferring a static match over a
+    /// `{param}` match over a `{*catch_all}` match at every level. Params are
+    /// only assembled once a full match is found, so a dead-end down the
+    /// static branch never leaks captures into the param/catch-all attempt
+    /// at the same level.
+    fn match_node(node: &RouteNode<T>, segments: &[&str]) -> Option<RouteMatch<T>> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return node.handler.map(|handler| (handler, Vec::new()));
+        };
 
-        if path_tokens.len() != pattern_tokens.len() {
-            return false;
+        if let Some(child) = node.static_children.get(*segment) {
+            if let Some(found) = Self::match_node(child, rest) {
+                return Some(found);
+            }
         }
 
-        for (path_token, pattern_token) in path_tokens.into_iter().zip(pattern_tokens) {
-            let wildcard = pattern_token.starts_with("{") && pattern_token.ends_with("}");
-            if path_token != pattern_token && !wildcard {
-                return false;
+        if let Some((name, child)) = &node.param_child {
+            if let Some((handler, mut params)) = Self::match_node(child, rest) {
+                params.push((name.clone(), segment.to_string()));
+                return Some((handler, params));
             }
         }
 
-        return true;
+        if let Some((name, handler)) = &node.catch_all {
+            return Some((*handler, vec![(name.clone(), segments.join("/"))]));
+        }
+
+        None
     }
 
     fn find(
@@ -133,20 +597,63 @@ impl<T: 'static> CallbackPathManager<T> {
         method: Method,
         path: &str,
     ) -> Option<(&'static Callback<T>, HashMap<String, String>)> {
-        let path = path.split("?").collect::<Vec<_>>()[0];
-        self.handlers[method as usize]
-            .iter()
-            .find(|(pattern, _)| CallbackPathManager::<T>::compare(path, pattern))
-            .and_then(|(pattern, handler)| {
-                Some((*handler, CallbackPathManager::<T>::extract(path, pattern)))
-            })
+        let path = path.split('?').next().unwrap_or(path);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        Self::match_node(&self.routes[method as usize], &segments)
+            .map(|(handler, params)| (handler, params.into_iter().collect()))
     }
 }
 
 pub struct WebService<'a, T: 'static> {
     addr: &'a str,
-    path_manager: CallbackPathManager<T>,
+    path_manager: Arc<CallbackPathManager<T>>,
     shared_data: Arc<Mutex<T>>,
+    mime_types: HashMap<String, String>,
+    static_mounts: Vec<(String, PathBuf)>,
+    middlewares: Arc<Vec<Box<dyn Middleware<T>>>>,
+    /// How long a connection may take to send a complete request (headers +
+    /// body) before it is abandoned with `408 Request Timeout`.
+    request_timeout: Duration,
+    /// How long a keep-alive connection may sit idle between requests before
+    /// it is closed.
+    keep_alive_timeout: Duration,
+}
+
+/// Everything a connection handler needs beyond the socket itself, bundled
+/// so it can be cloned and handed to `tokio::spawn` as a single value
+/// instead of as a long list of arguments.
+struct ConnectionContext<T: 'static> {
+    path_manager: Arc<CallbackPathManager<T>>,
+    shared_data: Arc<Mutex<T>>,
+    mime_types: HashMap<String, String>,
+    static_mounts: Vec<(String, PathBuf)>,
+    middlewares: Arc<Vec<Box<dyn Middleware<T>>>>,
+    request_timeout: Duration,
+    keep_alive_timeout: Duration,
+}
+
+impl<T: 'static> Clone for ConnectionContext<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path_manager: self.path_manager.clone(),
+            shared_data: self.shared_data.clone(),
+            mime_types: self.mime_types.clone(),
+            static_mounts: self.static_mounts.clone(),
+            middlewares: self.middlewares.clone(),
+            request_timeout: self.request_timeout,
+            keep_alive_timeout: self.keep_alive_timeout,
+        }
+    }
+}
+
+/// A fully-read request off the wire, before it's matched against any route.
+struct ParsedRequest {
+    method: Method,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    keep_alive: bool,
 }
 
 #[derive(Debug)]
@@ -157,14 +664,258 @@ pub struct Request<'a, T: 'static> {
     pub args: HashMap<String, String>,
     pub stream: &'a TcpStream,
     pub body: Vec<u8>,
+    pub method: Method,
+    /// Request headers, keyed by lower-cased header name.
+    pub headers: HashMap<String, String>,
+    /// Cookies sent in the request's `Cookie` header, keyed by name.
+    pub cookies: HashMap<String, String>,
 }
 
-impl<'a, T: Send + Sync> WebService<'a, T> {
+// Written by hand rather than derived: `#[derive(Clone)]` would add a
+// `T: Clone` bound, but `request.clone()` is called in `handle_connection`
+// where `T` is only bounded by `Send + Sync + 'static` (see the identical
+// reasoning above `impl<T: 'static> Clone for RouteNode<T>`).
+impl<'a, T: 'static> Clone for Request<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared_data: self.shared_data.clone(),
+            params: self.params.clone(),
+            args: self.args.clone(),
+            stream: self.stream,
+            body: self.body.clone(),
+            method: self.method,
+            headers: self.headers.clone(),
+            cookies: self.cookies.clone(),
+        }
+    }
+}
+
+/// A cross-cutting hook run around every request, matched or not. `before`
+/// can short-circuit the request by returning `Some(Response)`, skipping
+/// the matched handler entirely (this is how CORS answers preflight `OPTIONS` requests
+/// that have no registered handler); otherwise every registered middleware's
+/// `after` runs over the eventual response, in reverse registration order.
+/// This is the extension point logging, auth gates, CORS and similar
+/// features build on.
+pub trait Middleware<T>: Send + Sync {
+    fn before(&self, _req: &mut Request<T>) -> Option<Response> {
+        None
+    }
+
+    fn after(&self, _req: &Request<T>, res: Response) -> Response {
+        res
+    }
+}
+
+/// Runs every middleware's `before` hook in registration order, stopping at
+/// (and returning) the first short-circuit response.
+fn apply_before_middlewares<T>(
+    middlewares: &[Box<dyn Middleware<T>>],
+    request: &mut Request<T>,
+) -> Option<Response> {
+    for middleware in middlewares {
+        if let Some(response) = middleware.before(request) {
+            return Some(response);
+        }
+    }
+    None
+}
+
+/// Runs every middleware's `after` hook over `response`, in reverse
+/// registration order.
+fn apply_after_middlewares<T>(
+    middlewares: &[Box<dyn Middleware<T>>],
+    request: &Request<T>,
+    response: Response,
+) -> Response {
+    middlewares
+        .iter()
+        .rev()
+        .fold(response, |response, middleware| middleware.after(request, response))
+}
+
+/// Configuration for the CORS middleware installed by [`WebService::cors`].
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests. `"*"` allows any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+}
+
+struct CorsMiddleware {
+    config: CorsConfig,
+}
+
+impl CorsMiddleware {
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        self.config
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    fn allow_headers(&self, origin: &str) -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+        headers.insert("Access-Control-Allow-Origin".to_string(), origin.to_string());
+        headers.insert(
+            "Access-Control-Allow-Methods".to_string(),
+            self.config.allowed_methods.join(", "),
+        );
+        headers.insert(
+            "Access-Control-Allow-Headers".to_string(),
+            self.config.allowed_headers.join(", "),
+        );
+        if self.config.allow_credentials {
+            headers.insert("Access-Control-Allow-Credentials".to_string(), "true".to_string());
+        }
+        headers
+    }
+}
+
+impl<T> Middleware<T> for CorsMiddleware {
+    fn before(&self, req: &mut Request<T>) -> Option<Response> {
+        let origin = req.headers.get("origin")?;
+        if !self.is_allowed_origin(origin) {
+            return None;
+        }
+
+        // Preflight requests never reach a user handler (the router has no
+        // concept of OPTIONS-as-probe), so CORS must answer them directly.
+        if req.method == Method::OPTIONS {
+            return Some(Response::NO_CONTENT(self.allow_headers(origin)));
+        }
+
+        None
+    }
+
+    fn after(&self, req: &Request<T>, res: Response) -> Response {
+        let Some(origin) = req.headers.get("origin") else {
+            return res;
+        };
+        if !self.is_allowed_origin(origin) {
+            return res;
+        }
+
+        let mut builder = res.builder();
+        for (name, value) in self.allow_headers(origin) {
+            builder = builder.header(name, value);
+        }
+        builder.build()
+    }
+}
+
+/// Builds a value out of an incoming request, short-circuiting with a
+/// `Response` (typically `WebError::BAD_REQUEST`) on failure. Implemented by
+/// [`Json`], [`Query`] and [`PathParams`]; handlers registered via
+/// [`WebService::register_typed`] can take any combination of these instead
+/// of hand-parsing `req.body`/`req.args`/`req.params` themselves.
+pub trait FromRequest<T>: Sized {
+    fn from_request(req: &Request<T>) -> Result<Self, Response>;
+}
+
+/// Deserializes the request body as JSON.
+pub struct Json<D>(pub D);
+
+impl<T, D: serde::de::DeserializeOwned> FromRequest<T> for Json<D> {
+    fn from_request(req: &Request<T>) -> Result<Self, Response> {
+        serde_json::from_slice(&req.body)
+            .map(Json)
+            .map_err(|_| Response::ERROR(WebError::BAD_REQUEST))
+    }
+}
+
+/// Builds a `serde_json::Value` object out of string-keyed/string-valued
+/// params, coercing each value to a bool/number where it parses as one.
+/// Query/path params arrive as plain strings off the wire, but the
+/// overwhelmingly common target type for them (`id: u32`, `page: u32`,
+/// flags as `bool`) isn't a string, and `serde_json` won't coerce a JSON
+/// string into a number on its own.
+fn coerce_params_to_json(params: &HashMap<String, String>) -> serde_json::Value {
+    serde_json::Value::Object(
+        params
+            .iter()
+            .map(|(key, value)| (key.clone(), coerce_scalar_to_json(value)))
+            .collect(),
+    )
+}
+
+fn coerce_scalar_to_json(value: &str) -> serde_json::Value {
+    if let Ok(b) = value.parse::<bool>() {
+        serde_json::Value::Bool(b)
+    } else if let Ok(n) = value.parse::<i64>() {
+        serde_json::Value::Number(n.into())
+    } else if let Some(n) = value.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+        serde_json::Value::Number(n)
+    } else {
+        serde_json::Value::String(value.to_string())
+    }
+}
+
+/// Deserializes the request's query-string parameters (`req.args`).
+pub struct Query<D>(pub D);
+
+impl<T, D: serde::de::DeserializeOwned> FromRequest<T> for Query<D> {
+    fn from_request(req: &Request<T>) -> Result<Self, Response> {
+        serde_json::from_value(coerce_params_to_json(&req.args))
+            .map(Query)
+            .map_err(|_| Response::ERROR(WebError::BAD_REQUEST))
+    }
+}
+
+/// Deserializes the route's captured path parameters (`req.params`).
+pub struct PathParams<D>(pub D);
+
+impl<T, D: serde::de::DeserializeOwned> FromRequest<T> for PathParams<D> {
+    fn from_request(req: &Request<T>) -> Result<Self, Response> {
+        serde_json::from_value(coerce_params_to_json(&req.params))
+            .map(PathParams)
+            .map_err(|_| Response::ERROR(WebError::BAD_REQUEST))
+    }
+}
+
+/// A handler taking one or more [`FromRequest`] extractors instead of a raw
+/// `Request<T>`, registered via [`WebService::register_typed`].
+pub trait TypedHandler<T, Args>: Send + Sync {
+    fn call(&self, req: Request<T>) -> Response;
+}
+
+macro_rules! impl_typed_handler {
+    ($($extractor:ident => $arg:ident),+) => {
+        impl<T, F, $($extractor),+> TypedHandler<T, ($($extractor,)+)> for F
+        where
+            F: Fn($($extractor),+) -> Response + Send + Sync,
+            $($extractor: FromRequest<T>,)+
+        {
+            fn call(&self, req: Request<T>) -> Response {
+                $(
+                    let $arg = match $extractor::from_request(&req) {
+                        Ok(value) => value,
+                        Err(response) => return response,
+                    };
+                )+
+                (self)($($arg),+)
+            }
+        }
+    };
+}
+
+impl_typed_handler!(A => a);
+impl_typed_handler!(A => a, B => b);
+impl_typed_handler!(A => a, B => b, C => c);
+
+impl<'a, T: Send + Sync + 'static> WebService<'a, T> {
     pub fn new(addr: &'a str, shared_data: T) -> Self {
         Self {
             addr,
-            path_manager: CallbackPathManager::<T>::new(),
+            path_manager: Arc::new(CallbackPathManager::<T>::new()),
             shared_data: Arc::new(Mutex::new(shared_data)),
+            mime_types: default_mime_types(),
+            static_mounts: Vec::new(),
+            middlewares: Arc::new(Vec::new()),
+            request_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
         }
     }
 
@@ -174,115 +925,569 @@ impl<'a, T: Send + Sync> WebService<'a, T> {
         method: Method,
         handler: &'static Callback<T>,
     ) -> Self {
-        self.path_manager.register(method, pattern, handler);
+        Arc::get_mut(&mut self.path_manager)
+            .expect("routes can only be registered before the service starts listening")
+            .register(method, pattern, handler);
+        self
+    }
+
+    /// Like [`WebService::register`], but `handler` takes [`FromRequest`]
+    /// extractors (e.g. [`Json`], [`Query`], [`PathParams`]) instead of a raw
+    /// `Request<T>`, so it doesn't need to hand-parse the body/query/params.
+    pub fn register_typed<Args: 'static>(
+        mut self,
+        pattern: &str,
+        method: Method,
+        handler: &'static dyn TypedHandler<T, Args>,
+    ) -> Self {
+        let wrapped: &'static Callback<T> = Box::leak(Box::new(move |req: Request<T>| handler.call(req)));
+        Arc::get_mut(&mut self.path_manager)
+            .expect("routes can only be registered before the service starts listening")
+            .register(method, pattern, wrapped);
+        self
+    }
+
+    /// Overrides how long a connection may take to send a complete request
+    /// (headers + body) before it is abandoned with `408 Request Timeout`.
+    /// Defaults to 30 seconds.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Overrides how long a keep-alive connection may sit idle between
+    /// requests before it is closed. Defaults to 5 seconds.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = timeout;
         self
     }
 
-    fn handle_connection(&mut self, mut stream: TcpStream) {
-        let mut headers = [httparse::EMPTY_HEADER; 64];
-        let mut req = httparse::Request::new(&mut headers);
-        let mut data = vec![]; 
+    /// Serves files on disk under `fs_path` for any request path beneath
+    /// `mount`, with `Content-Type` resolved from each file's extension and
+    /// `ETag`/`Last-Modified`-based conditional caching handled automatically.
+    pub fn serve_dir(mut self, mount: &str, fs_path: &str) -> Self {
+        self.static_mounts
+            .push((mount.trim_end_matches('/').to_string(), PathBuf::from(fs_path)));
+        self
+    }
+
+    /// Loads extension -> content-type mappings from an `/etc/mime.types`-style
+    /// file, overriding the bundled defaults where extensions collide.
+    pub fn mime_types_file(mut self, path: &str) -> Self {
+        if let Ok(contents) = fs::read_to_string(path) {
+            self.mime_types.extend(parse_mime_types_file(&contents));
+        }
+        self
+    }
+
+    /// Registers a middleware to run around every request, matched or not. Middlewares
+    /// run in registration order on the way in (`before`) and reverse order
+    /// on the way out (`after`).
+    pub fn wrap<M: Middleware<T> + 'static>(mut self, middleware: M) -> Self {
+        Arc::get_mut(&mut self.middlewares)
+            .expect("middlewares can only be registered before the service starts listening")
+            .push(Box::new(middleware));
+        self
+    }
+
+    /// Installs CORS handling: allowed origins get a matching
+    /// `Access-Control-Allow-Origin`, and `OPTIONS` preflight requests are
+    /// answered directly with `204` instead of falling through to the router.
+    pub fn cors(self, config: CorsConfig) -> Self {
+        self.wrap(CorsMiddleware { config })
+    }
+
+    /// Resolves `path` against the registered static mounts, rejecting any
+    /// attempt to escape the mount's directory via `..` segments.
+    fn resolve_static_file(static_mounts: &[(String, PathBuf)], path: &str) -> Option<PathBuf> {
+        for (mount, base) in static_mounts {
+            let rest = match path.strip_prefix(mount.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                _ => continue,
+            };
+
+            let rest = rest.trim_start_matches('/');
+            if rest.split('/').any(|segment| segment == "..") {
+                continue;
+            }
+
+            let full_path = base.join(rest);
+            if full_path.is_file() {
+                return Some(full_path);
+            }
+        }
+
+        None
+    }
+
+    /// Serves requests from a single connection, looping to handle further
+    /// pipelined/keep-alive requests on the same socket until the client
+    /// sends `Connection: close`, goes idle past `keep_alive_timeout`, or a
+    /// request fails to arrive within `request_timeout` (answered with
+    /// `408 Request Timeout`).
+    async fn handle_connection(mut stream: TcpStream, ctx: ConnectionContext<T>) {
+        let ConnectionContext {
+            path_manager,
+            shared_data,
+            mime_types,
+            static_mounts,
+            middlewares,
+            request_timeout,
+            keep_alive_timeout,
+        } = ctx;
+
+        // Bytes already read off the socket but not yet consumed by a
+        // request. Persists across loop iterations so that a peer pipelining
+        // (or simply being fast with) back-to-back requests never has the
+        // tail of one read swallowed and discarded along with the one before it.
+        let mut buffer = Vec::new();
+
         loop {
-            let mut buffer = [0; 2048];
-            if let Ok(n) = stream.read(&mut buffer) {
-                data.extend_from_slice(&buffer[..n]);
+            let parsed = match Self::read_request(&mut stream, &mut buffer, request_timeout).await {
+                Some(parsed) => parsed,
+                None => {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 408 Request Timeout\r\nConnection: close\r\n\r\n")
+                        .await;
+                    return;
+                }
+            };
+
+            let path_and_args = parsed.path.as_str();
+            let mut path = path_and_args;
+            let mut args = HashMap::new();
+
+            if path_and_args.contains('?') {
+                let path_and_params = path_and_args.split('?').collect::<Vec<_>>();
+                path = path_and_params[0];
+
+                args = path_and_params[1]
+                    .split('&')
+                    .map(|param| {
+                        let mut name_value = param.split('=').collect::<VecDeque<_>>();
+                        (name_value.pop_front(), name_value.pop_front())
+                    })
+                    .filter_map(|(name, value)| match (name, value) {
+                        (Some(name), Some(value)) => Some((name.to_string(), value.to_string())),
+                        _ => None,
+                    })
+                    .collect::<HashMap<_, _>>();
+            }
+
+            let handler = path_manager.find(parsed.method, path);
+            let static_file = if handler.is_none() && parsed.method == Method::GET {
+                Self::resolve_static_file(&static_mounts, path)
+            } else {
+                None
+            };
+
+            let cookies = parsed
+                .headers
+                .get("cookie")
+                .map(|header| parse_cookies(header))
+                .unwrap_or_default();
+
+            let mut request = Request {
+                shared_data: shared_data.clone(),
+                args,
+                params: HashMap::new(),
+                stream: &stream,
+                body: parsed.body,
+                method: parsed.method,
+                headers: parsed.headers,
+                cookies,
+            };
+
+            let short_circuit = apply_before_middlewares(&middlewares, &mut request);
 
-                if n == 2048 {
-                    continue;
+            let response = match short_circuit {
+                Some(response) => Some(response),
+                None => match (handler, static_file) {
+                    (Some((handler, params)), _) => {
+                        request.params = params;
+                        Some(handler(request.clone()))
+                    }
+                    (None, Some(file_path)) => Some(Response::FILE(file_path)),
+                    (None, None) => path_manager
+                        .find(Method::GET, "404")
+                        .map(|(handler, params)| {
+                            request.params = params;
+                            handler(request.clone())
+                        }),
+                },
+            };
+
+            match response {
+                Some(response) => {
+                    let response = apply_after_middlewares(&middlewares, &request, response);
+                    let if_none_match = request.headers.get("if-none-match").cloned();
+                    let if_modified_since = request.headers.get("if-modified-since").cloned();
+                    Self::write_response(
+                        &mut stream,
+                        response,
+                        &mime_types,
+                        if_none_match,
+                        if_modified_since,
+                    )
+                    .await;
                 }
+                None => {
+                    let _ = stream.write_all(b"HTTP/1.1 404 NOT FOUND").await;
+                }
+            }
+
+            if !parsed.keep_alive {
+                return;
             }
 
-            break;
+            // A pipelined request may already be sitting in `buffer`; only
+            // wait on the socket for a fresh one if it's empty.
+            if buffer.is_empty()
+                && !Self::connection_has_more_data(&mut stream, keep_alive_timeout).await
+            {
+                return;
+            }
         }
+    }
 
-        req.parse(&data).unwrap();
+    /// Reads a single request off `stream`: headers first (answering
+    /// `Expect: 100-continue` as soon as they're in), then exactly
+    /// `Content-Length` body bytes (decoding `Transfer-Encoding: chunked`
+    /// bodies as they arrive). Returns `None` if `timeout` elapses before the
+    /// request is complete, or the peer closes the connection early.
+    ///
+    /// `buffer` holds bytes already read off `stream` but not yet consumed
+    /// by a request; it's owned by the caller and persists across calls on
+    /// the same connection, so bytes belonging to the next pipelined request
+    /// that arrive in the same read as this one's tail are carried over
+    /// instead of discarded.
+    async fn read_request(
+        stream: &mut TcpStream,
+        buffer: &mut Vec<u8>,
+        timeout: Duration,
+    ) -> Option<ParsedRequest> {
+        let deadline = Instant::now() + timeout;
+        let header_end = loop {
+            if let Some(pos) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break pos + 4;
+            }
 
-        if req.method.is_none() || req.path.is_none() || req.headers.is_empty() {
-            let _ = stream.write_all("HTTP/1.1 500 INTERNAL SERVER ERROR".as_bytes());
-            return;
+            Self::read_more(stream, buffer, deadline).await?;
+        };
+
+        let mut header_storage = [httparse::EMPTY_HEADER; 64];
+        let mut parsed = httparse::Request::new(&mut header_storage);
+        if parsed.parse(&buffer[..header_end]).ok()?.is_partial() {
+            return None;
         }
 
-        // Extract body
-        let body_start = data.windows(4).position(|window| window == b"\r\n\r\n");
-        let body = if let Some(body_start) = body_start {
-            data[body_start + 4..].to_vec()
+        let method = parsed.method?.parse().unwrap_or(Method::GET);
+        let path = parsed.path?.to_string();
+        let headers: HashMap<String, String> = parsed
+            .headers
+            .iter()
+            .filter_map(|header| {
+                std::str::from_utf8(header.value)
+                    .ok()
+                    .map(|value| (header.name.to_lowercase(), value.to_string()))
+            })
+            .collect();
+
+        buffer.drain(..header_end);
+
+        if headers
+            .get("expect")
+            .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+        {
+            let _ = stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await;
+        }
+
+        let is_chunked = headers
+            .get("transfer-encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+
+        let body = if is_chunked {
+            Self::read_chunked_body(stream, buffer, deadline).await?
         } else {
-            vec![]
+            let content_length: usize = headers
+                .get("content-length")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0);
+
+            while buffer.len() < content_length {
+                Self::read_more(stream, buffer, deadline).await?;
+            }
+            buffer.drain(..content_length).collect()
+        };
+
+        let keep_alive = match headers.get("connection").map(|value| value.to_lowercase()) {
+            Some(value) if value == "close" => false,
+            Some(value) if value == "keep-alive" => true,
+            _ => true, // HTTP/1.1 connections default to keep-alive.
         };
 
+        Some(ParsedRequest {
+            method,
+            path,
+            headers,
+            body,
+            keep_alive,
+        })
+    }
+
+    /// Decodes a `Transfer-Encoding: chunked` body directly out of `buffer`,
+    /// pulling more bytes off `stream` as needed until the terminating
+    /// zero-length chunk. Anything left in `buffer` afterwards (e.g. the
+    /// start of the next pipelined request) is left for the next
+    /// [`Self::read_request`] call.
+    async fn read_chunked_body(
+        stream: &mut TcpStream,
+        buffer: &mut Vec<u8>,
+        deadline: Instant,
+    ) -> Option<Vec<u8>> {
+        let mut decoded = Vec::new();
+
+        loop {
+            let line_end = loop {
+                if let Some(pos) = buffer.windows(2).position(|window| window == b"\r\n") {
+                    break pos;
+                }
+                Self::read_more(stream, buffer, deadline).await?;
+            };
+
+            let chunk_size =
+                usize::from_str_radix(std::str::from_utf8(&buffer[..line_end]).ok()?.trim(), 16)
+                    .ok()?;
+            buffer.drain(..line_end + 2);
+
+            if chunk_size == 0 {
+                // Consume the trailer section through its closing blank line,
+                // so none of this message is left behind in `buffer`.
+                loop {
+                    let line_end = loop {
+                        if let Some(pos) = buffer.windows(2).position(|window| window == b"\r\n") {
+                            break pos;
+                        }
+                        Self::read_more(stream, buffer, deadline).await?;
+                    };
+                    buffer.drain(..line_end + 2);
+                    if line_end == 0 {
+                        break;
+                    }
+                }
+                break;
+            }
+
+            while buffer.len() < chunk_size + 2 {
+                Self::read_more(stream, buffer, deadline).await?;
+            }
+
+            decoded.extend_from_slice(&buffer[..chunk_size]);
+            buffer.drain(..chunk_size + 2);
+        }
+
+        Some(decoded)
+    }
 
-        // TODO: https://lib.rs/crates/httparse
-        let method = req.method.unwrap().parse().unwrap_or(Method::GET);
-        let path_and_args = req.path.unwrap_or(&"/");
-        let mut path = path_and_args;
-        let mut args = HashMap::new();
+    /// Reads whatever is available into `buffer`, respecting `deadline`.
+    /// Returns `None` on timeout or if the peer closes the connection.
+    async fn read_more(stream: &mut TcpStream, buffer: &mut Vec<u8>, deadline: Instant) -> Option<()> {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
 
-        // Extract params
-        if path_and_args.contains("?") {
-            let path_and_params = path_and_args.split('?').collect::<Vec<_>>();
-            path = path_and_params[0];
+        let mut chunk = [0; 2048];
+        let n = tokio::time::timeout(remaining, stream.read(&mut chunk))
+            .await
+            .ok()?
+            .ok()?;
 
-            args = path_and_params[1]
-                .split('&')
-                .map(|param| {
-                    let mut name_value = param.split('=').collect::<VecDeque<_>>();
-                    (name_value.pop_front(), name_value.pop_front())
-                })
-                .filter_map(|(name, value)| match (name, value) {
-                    (Some(name), Some(value)) => Some((name.to_string(), value.to_string())),
-                    _ => None,
-                })
-                .collect::<HashMap<_, _>>();
+        if n == 0 {
+            return None;
         }
+        buffer.extend_from_slice(&chunk[..n]);
+        Some(())
+    }
 
-        let handler = self
-            .path_manager
-            .find(method, path)
-            .or(self.path_manager.find(Method::GET, "404"));
+    /// Peeks for another request on a keep-alive connection without
+    /// consuming it, so the next call to [`Self::read_request`] can read it
+    /// from scratch. Returns `false` if the connection goes idle past
+    /// `timeout` or the peer has closed it.
+    async fn connection_has_more_data(stream: &mut TcpStream, timeout: Duration) -> bool {
+        let mut probe = [0u8; 1];
+        matches!(
+            tokio::time::timeout(timeout, stream.peek(&mut probe)).await,
+            Ok(Ok(n)) if n > 0
+        )
+    }
 
-        if let Some((handler, params)) = handler {
-            let shared_data = self.shared_data.clone();
+    /// Writes a status line, `headers`, one `Set-Cookie` line per cookie and
+    /// `body` (if non-empty) to `stream`. The single place that knows the
+    /// wire format, so every `Response` variant goes through it.
+    async fn write_raw_response(
+        stream: &mut TcpStream,
+        status: u16,
+        mut headers: HashMap<String, String>,
+        cookies: &[Cookie],
+        body: &[u8],
+    ) {
+        // 204/304 (and 1xx) responses must not carry a body per RFC 7230 §3.3.2,
+        // so Content-Length is omitted for those; every other status gets one
+        // regardless of body length, since an absent Content-Length/
+        // Transfer-Encoding would break keep-alive framing for the next
+        // response on this connection.
+        if !matches!(status, 100..=199 | 204 | 304) {
+            headers
+                .entry("Content-Length".to_string())
+                .or_insert_with(|| body.len().to_string());
+        }
 
-            tokio::spawn(async move {
-                let response = handler(Request {
-                    shared_data,
-                    args,
-                    params,
-                    stream: &stream,
-                    body,
-                });
-
-                let _ = if let Some(content_type) = response.get_str("content_type") {
-                    let _ = stream.write_all(
-                        format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\n\r\n", content_type)
-                            .as_bytes(),
-                    );
-
-                    match response {
-                        Response::HTML(html) => stream.write_all(html.as_bytes()),
-                        Response::JS(text) => stream.write_all(text.as_bytes()),
-                        Response::XML(text) => stream.write_all(text.as_bytes()),
-                        Response::CSS(text) => stream.write_all(text.as_bytes()),
-                        Response::TEXT(text) => stream.write_all(text.as_bytes()),
-                        Response::JSON(json) => stream.write_all(json.to_string().as_bytes()),
-                        Response::PNG(bytes) => stream.write_all(&bytes),
-                        Response::JPG(bytes) => stream.write_all(&bytes),
-                        Response::GIF(bytes) => stream.write_all(&bytes),
-                        Response::WEBP(bytes) => stream.write_all(&bytes),
-                        Response::SVG(text) => stream.write_all(text.as_bytes()),
-                        _ => stream.write_all("HTTP/1.1 500 INTERNAL SERVER ERROR".as_bytes()),
-                    }
-                } else {
-                    stream.write_all("HTTP/1.1 500 INTERNAL SERVER ERROR".as_bytes())
-                };
-            });
+        let mut out = format!("HTTP/1.1 {} {}\r\n", status, reason_phrase(status));
+        for (name, value) in &headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        for cookie in cookies {
+            out.push_str(&format!("Set-Cookie: {}\r\n", cookie.to_header_value()));
+        }
+        out.push_str("\r\n");
+
+        let _ = stream.write_all(out.as_bytes()).await;
+        if !body.is_empty() {
+            let _ = stream.write_all(body).await;
+        }
+    }
+
+    /// Renders the default status/content-type/body for every `Response`
+    /// variant except `FILE`, `NO_CONTENT` and `CUSTOM`, which carry their
+    /// own framing and are handled directly in [`Self::write_response`].
+    fn render_body(response: &Response) -> (u16, Option<&'static str>, Vec<u8>) {
+        match response {
+            Response::ERROR(err) => (err.status_code(), Some("text/plain"), err.message().as_bytes().to_vec()),
+            Response::HTML(text) | Response::JS(text) | Response::XML(text)
+            | Response::CSS(text) | Response::TEXT(text) | Response::SVG(text) => {
+                (200, response.get_str("content_type"), text.clone().into_bytes())
+            }
+            Response::JSON(json) => (200, response.get_str("content_type"), json.to_string().into_bytes()),
+            Response::PNG(bytes) | Response::JPG(bytes) | Response::GIF(bytes)
+            | Response::WEBP(bytes) => (200, response.get_str("content_type"), bytes.clone()),
+            Response::FILE(_) | Response::NO_CONTENT(_) | Response::CUSTOM(..) => {
+                unreachable!("handled before render_body is called")
+            }
+        }
+    }
+
+    async fn write_response(
+        stream: &mut TcpStream,
+        response: Response,
+        mime_types: &HashMap<String, String>,
+        if_none_match: Option<String>,
+        if_modified_since: Option<String>,
+    ) {
+        let (response, overrides) = match response {
+            Response::CUSTOM(inner, overrides) => (*inner, overrides),
+            response => (
+                response,
+                ResponseOverrides {
+                    status: None,
+                    headers: HashMap::new(),
+                    cookies: Vec::new(),
+                },
+            ),
+        };
+
+        if let Response::FILE(path) = response {
+            Self::write_file_response(stream, &path, if_none_match, if_modified_since, mime_types, overrides)
+                .await;
+            return;
+        }
+
+        let ResponseOverrides { status: status_override, headers: extra_headers, cookies } = overrides;
+
+        if let Response::NO_CONTENT(headers) = response {
+            let mut headers = headers;
+            headers.extend(extra_headers);
+            Self::write_raw_response(stream, status_override.unwrap_or(204), headers, &cookies, &[]).await;
+            return;
+        }
+
+        let (status, content_type, body) = Self::render_body(&response);
+        let mut headers = extra_headers;
+        if let Some(content_type) = content_type {
+            headers
+                .entry("Content-Type".to_string())
+                .or_insert_with(|| content_type.to_string());
+        }
+        Self::write_raw_response(stream, status_override.unwrap_or(status), headers, &cookies, &body).await;
+    }
+
+    /// Serves `path` from disk, answering `304 Not Modified` when the
+    /// request's `If-None-Match`/`If-Modified-Since` headers show the
+    /// client's cached copy is still fresh. `If-None-Match` takes precedence
+    /// over `If-Modified-Since` when both are present, per RFC 7232.
+    /// `overrides` comes from a [`Response::CUSTOM`] wrapping the `FILE`
+    /// response, if any.
+    async fn write_file_response(
+        stream: &mut TcpStream,
+        path: &Path,
+        if_none_match: Option<String>,
+        if_modified_since: Option<String>,
+        mime_types: &HashMap<String, String>,
+        overrides: ResponseOverrides,
+    ) {
+        let ResponseOverrides { status: status_override, headers: extra_headers, cookies } = overrides;
+        let cookies = &cookies;
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                Self::write_raw_response(stream, 404, HashMap::new(), &[], &[]).await;
+                return;
+            }
+        };
+
+        let mtime_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let etag = format!("\"{:x}-{:x}\"", mtime_secs, metadata.len());
+        let last_modified = http_date(mtime_secs);
+
+        let not_modified = if let Some(if_none_match) = if_none_match.as_deref() {
+            if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == etag || candidate == "*")
+        } else if let Some(if_modified_since) = if_modified_since.as_deref() {
+            parse_http_date(if_modified_since).is_some_and(|since| since >= mtime_secs)
         } else {
-            let _ = stream.write_all("HTTP/1.1 404 NOT FOUND".as_bytes());
+            false
+        };
+
+        if not_modified {
+            let mut headers = extra_headers;
+            headers.insert("ETag".to_string(), etag);
+            headers.insert("Last-Modified".to_string(), last_modified);
+            Self::write_raw_response(stream, status_override.unwrap_or(304), headers, cookies, &[]).await;
+            return;
+        }
+
+        match fs::read(path) {
+            Ok(bytes) => {
+                let mut headers = extra_headers;
+                headers
+                    .entry("Content-Type".to_string())
+                    .or_insert_with(|| mime_type_for_path(mime_types, path).to_string());
+                headers.insert("ETag".to_string(), etag);
+                headers.insert("Last-Modified".to_string(), last_modified);
+                Self::write_raw_response(stream, status_override.unwrap_or(200), headers, cookies, &bytes).await;
+            }
+            Err(_) => {
+                Self::write_raw_response(stream, 500, HashMap::new(), &[], &[]).await;
+            }
         }
     }
 
     pub async fn listen(&mut self, open_in_browser: bool) {
-        let listener = TcpListener::bind(self.addr).unwrap();
+        let listener = TcpListener::bind(self.addr).await.unwrap();
         let url = format!("http://{}", self.addr);
 
         if open_in_browser {
@@ -291,8 +1496,592 @@ impl<'a, T: Send + Sync> WebService<'a, T> {
 
         println!("Listening on {}", url);
 
-        while let Ok((stream, _socket)) = listener.accept() {
-            self.handle_connection(stream);
+        while let Ok((stream, _socket)) = listener.accept().await {
+            let ctx = ConnectionContext {
+                path_manager: self.path_manager.clone(),
+                shared_data: self.shared_data.clone(),
+                mime_types: self.mime_types.clone(),
+                static_mounts: self.static_mounts.clone(),
+                middlewares: self.middlewares.clone(),
+                request_timeout: self.request_timeout,
+                keep_alive_timeout: self.keep_alive_timeout,
+            };
+
+            tokio::spawn(async move {
+                Self::handle_connection(stream, ctx).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn static_handler(_: Request<()>) -> Response {
+        Response::TEXT("static".to_string())
+    }
+
+    fn param_handler(_: Request<()>) -> Response {
+        Response::TEXT("param".to_string())
+    }
+
+    fn catch_all_handler(_: Request<()>) -> Response {
+        Response::TEXT("catch_all".to_string())
+    }
+
+    /// A live loopback connection, just to satisfy `Request::stream`'s
+    /// lifetime — the route-matching tests below never read from it.
+    async fn dummy_stream() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap().0 });
+        let client = TcpStream::connect(addr).await.unwrap();
+        accept.await.unwrap();
+        client
+    }
+
+    fn make_request(stream: &TcpStream) -> Request<'_, ()> {
+        Request {
+            shared_data: Arc::new(Mutex::new(())),
+            params: HashMap::new(),
+            args: HashMap::new(),
+            stream,
+            body: Vec::new(),
+            method: Method::GET,
+            headers: HashMap::new(),
+            cookies: HashMap::new(),
+        }
+    }
+
+    fn find_text(
+        manager: &CallbackPathManager<()>,
+        method: Method,
+        path: &str,
+        stream: &TcpStream,
+    ) -> Option<(String, HashMap<String, String>)> {
+        manager.find(method, path).map(|(handler, params)| {
+            let mut req = make_request(stream);
+            req.method = method;
+            req.params = params.clone();
+            let text = match handler(req) {
+                Response::TEXT(text) => text,
+                other => panic!("expected Response::TEXT, got {other:?}"),
+            };
+            (text, params)
+        })
+    }
+
+    #[tokio::test]
+    async fn static_route_wins_over_param_and_catch_all() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/a/{id}", &param_handler);
+        manager.register(Method::GET, "/a/{*rest}", &catch_all_handler);
+        manager.register(Method::GET, "/a/b", &static_handler);
+
+        let stream = dummy_stream().await;
+        let (text, params) = find_text(&manager, Method::GET, "/a/b", &stream).unwrap();
+        assert_eq!(text, "static");
+        assert!(params.is_empty());
+    }
+
+    #[tokio::test]
+    async fn param_route_wins_over_catch_all() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/a/{*rest}", &catch_all_handler);
+        manager.register(Method::GET, "/a/{id}", &param_handler);
+
+        let stream = dummy_stream().await;
+        let (text, params) = find_text(&manager, Method::GET, "/a/123", &stream).unwrap();
+        assert_eq!(text, "param");
+        assert_eq!(params.get("id"), Some(&"123".to_string()));
+    }
+
+    #[tokio::test]
+    async fn catch_all_matches_remaining_segments() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/files/{*rest}", &catch_all_handler);
+
+        let stream = dummy_stream().await;
+        let (text, params) = find_text(&manager, Method::GET, "/files/a/b/c", &stream).unwrap();
+        assert_eq!(text, "catch_all");
+        assert_eq!(params.get("rest"), Some(&"a/b/c".to_string()));
+    }
+
+    #[tokio::test]
+    async fn routes_are_scoped_per_method() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/a", &static_handler);
+
+        let stream = dummy_stream().await;
+        assert!(find_text(&manager, Method::POST, "/a", &stream).is_none());
+    }
+
+    #[test]
+    fn unmatched_path_returns_none() {
+        let manager = CallbackPathManager::<()>::new();
+        assert!(manager.find(Method::GET, "/missing").is_none());
+    }
+
+    #[test]
+    fn default_matches_new() {
+        let manager: CallbackPathManager<()> = Default::default();
+        assert!(manager.find(Method::GET, "/anything").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "param `{id}` doesn't match `{user_id}`")]
+    fn register_panics_on_conflicting_param_names_at_the_same_position() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/users/{user_id}", &static_handler);
+        manager.register(Method::GET, "/users/{id}/posts", &static_handler);
+    }
+
+    #[tokio::test]
+    async fn register_allows_the_same_param_name_reused_at_the_same_position() {
+        let mut manager = CallbackPathManager::<()>::new();
+        manager.register(Method::GET, "/users/{id}", &static_handler);
+        manager.register(Method::GET, "/users/{id}/posts", &param_handler);
+
+        let stream = dummy_stream().await;
+        assert!(find_text(&manager, Method::GET, "/users/42", &stream).is_some());
+        let (text, params) = find_text(&manager, Method::GET, "/users/42/posts", &stream).unwrap();
+        assert_eq!(text, "param");
+        assert_eq!(params.get("id"), Some(&"42".to_string()));
+    }
+
+    #[test]
+    fn http_date_round_trips_through_parse_http_date() {
+        // 1994-11-06T08:49:37Z, the canonical RFC 7231 example timestamp.
+        let timestamp: u64 = 784111777;
+        let formatted = http_date(timestamp);
+        assert_eq!(formatted, "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date(&formatted), Some(timestamp));
+    }
+
+    #[test]
+    fn http_date_round_trips_for_unix_epoch() {
+        let formatted = http_date(0);
+        assert_eq!(parse_http_date(&formatted), Some(0));
+    }
+
+    #[test]
+    fn parse_http_date_rejects_garbage() {
+        assert_eq!(parse_http_date("not a date"), None);
+    }
+
+    struct RecordingMiddleware {
+        name: &'static str,
+        short_circuit: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware<()> for RecordingMiddleware {
+        fn before(&self, _req: &mut Request<()>) -> Option<Response> {
+            self.log.lock().unwrap().push(format!("{}:before", self.name));
+            if self.short_circuit {
+                Some(Response::TEXT(self.name.to_string()))
+            } else {
+                None
+            }
+        }
+
+        fn after(&self, _req: &Request<()>, res: Response) -> Response {
+            self.log.lock().unwrap().push(format!("{}:after", self.name));
+            res
         }
     }
+
+    #[tokio::test]
+    async fn before_hooks_run_in_registration_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let middlewares: Vec<Box<dyn Middleware<()>>> = vec![
+            Box::new(RecordingMiddleware { name: "a", short_circuit: false, log: log.clone() }),
+            Box::new(RecordingMiddleware { name: "b", short_circuit: false, log: log.clone() }),
+        ];
+
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        assert!(apply_before_middlewares(&middlewares, &mut request).is_none());
+        assert_eq!(*log.lock().unwrap(), vec!["a:before", "b:before"]);
+    }
+
+    #[tokio::test]
+    async fn before_hook_short_circuits_and_skips_later_middlewares() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let middlewares: Vec<Box<dyn Middleware<()>>> = vec![
+            Box::new(RecordingMiddleware { name: "a", short_circuit: true, log: log.clone() }),
+            Box::new(RecordingMiddleware { name: "b", short_circuit: false, log: log.clone() }),
+        ];
+
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        let response = apply_before_middlewares(&middlewares, &mut request);
+        assert!(matches!(response, Some(Response::TEXT(text)) if text == "a"));
+        assert_eq!(*log.lock().unwrap(), vec!["a:before"]);
+    }
+
+    #[tokio::test]
+    async fn after_hooks_run_in_reverse_registration_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let middlewares: Vec<Box<dyn Middleware<()>>> = vec![
+            Box::new(RecordingMiddleware { name: "a", short_circuit: false, log: log.clone() }),
+            Box::new(RecordingMiddleware { name: "b", short_circuit: false, log: log.clone() }),
+        ];
+
+        let stream = dummy_stream().await;
+        let request = make_request(&stream);
+        let response = apply_after_middlewares(&middlewares, &request, Response::TEXT("ok".to_string()));
+        assert!(matches!(response, Response::TEXT(text) if text == "ok"));
+        assert_eq!(*log.lock().unwrap(), vec!["b:after", "a:after"]);
+    }
+
+    fn cors(allowed_origins: &[&str]) -> CorsMiddleware {
+        CorsMiddleware {
+            config: CorsConfig {
+                allowed_origins: allowed_origins.iter().map(|s| s.to_string()).collect(),
+                allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+                allowed_headers: vec!["content-type".to_string()],
+                allow_credentials: false,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn cors_answers_preflight_for_allowed_origin() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.method = Method::OPTIONS;
+        request.headers.insert("origin".to_string(), "https://a.com".to_string());
+
+        let response = Middleware::<()>::before(&cors(&["https://a.com"]), &mut request);
+        let Some(Response::NO_CONTENT(headers)) = response else {
+            panic!("expected a NO_CONTENT preflight response, got {response:?}");
+        };
+        assert_eq!(headers.get("Access-Control-Allow-Origin"), Some(&"https://a.com".to_string()));
+        assert_eq!(headers.get("Access-Control-Allow-Methods"), Some(&"GET, POST".to_string()));
+    }
+
+    #[tokio::test]
+    async fn cors_rejects_disallowed_origin() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.method = Method::OPTIONS;
+        request.headers.insert("origin".to_string(), "https://evil.com".to_string());
+
+        assert!(Middleware::<()>::before(&cors(&["https://a.com"]), &mut request).is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_does_not_short_circuit_non_preflight_requests() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.headers.insert("origin".to_string(), "https://a.com".to_string());
+
+        assert!(Middleware::<()>::before(&cors(&["https://a.com"]), &mut request).is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_after_echoes_allowed_origin_into_response_headers() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.headers.insert("origin".to_string(), "https://a.com".to_string());
+
+        let response = Middleware::<()>::after(&cors(&["https://a.com"]), &request, Response::TEXT("ok".to_string()));
+        let Response::CUSTOM(_, overrides) = response else {
+            panic!("expected CORS to wrap the response in CUSTOM overrides, got {response:?}");
+        };
+        assert_eq!(overrides.headers.get("Access-Control-Allow-Origin"), Some(&"https://a.com".to_string()));
+    }
+
+    #[tokio::test]
+    async fn cors_after_leaves_response_unchanged_without_origin_header() {
+        let stream = dummy_stream().await;
+        let request = make_request(&stream);
+
+        let response = Middleware::<()>::after(&cors(&["https://a.com"]), &request, Response::TEXT("ok".to_string()));
+        assert!(matches!(response, Response::TEXT(text) if text == "ok"));
+    }
+
+    /// A connected loopback pair: `server` is what `read_request` et al. are
+    /// exercised against, `client` is used to write raw bytes at it.
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap().0 });
+        let client = TcpStream::connect(addr).await.unwrap();
+        let server = accept.await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn read_request_parses_a_simple_get() {
+        let (mut client, mut server) = connected_pair().await;
+        client
+            .write_all(b"GET /hello?x=1 HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        let parsed = WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(parsed.path, "/hello?x=1");
+        assert_eq!(parsed.method, Method::GET);
+        assert!(parsed.body.is_empty());
+        assert!(!parsed.keep_alive);
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_request_reads_exactly_content_length_bytes() {
+        let (mut client, mut server) = connected_pair().await;
+        client
+            .write_all(b"POST /submit HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+            .await
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        let parsed = WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(parsed.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn read_request_decodes_chunked_body() {
+        let (mut client, mut server) = connected_pair().await;
+        client
+            .write_all(
+                b"POST /submit HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        let parsed = WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(parsed.body, b"Wikipedia");
+    }
+
+    #[tokio::test]
+    async fn read_request_carries_pipelined_bytes_across_calls() {
+        let (mut client, mut server) = connected_pair().await;
+        // Both requests arrive in a single write, as they would if pipelined
+        // back-to-back by the peer.
+        client
+            .write_all(
+                b"GET /first HTTP/1.1\r\nConnection: keep-alive\r\n\r\n\
+                  GET /second HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        let first = WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(first.path, "/first");
+        // The second request's bytes arrived in the same read() as the
+        // first's tail; they must still be sitting in `buffer`.
+        assert!(!buffer.is_empty());
+
+        let second = WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(second.path, "/second");
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_request_times_out_when_headers_never_arrive() {
+        let (_client, mut server) = connected_pair().await;
+        let mut buffer = Vec::new();
+        let parsed =
+            WebService::<'_, ()>::read_request(&mut server, &mut buffer, Duration::from_millis(50)).await;
+        assert!(parsed.is_none());
+    }
+
+    #[tokio::test]
+    async fn connection_has_more_data_sees_pipelined_bytes() {
+        let (mut client, mut server) = connected_pair().await;
+        client.write_all(b"x").await.unwrap();
+        assert!(WebService::<'_, ()>::connection_has_more_data(&mut server, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn connection_has_more_data_times_out_when_idle() {
+        let (_client, mut server) = connected_pair().await;
+        assert!(
+            !WebService::<'_, ()>::connection_has_more_data(&mut server, Duration::from_millis(50)).await
+        );
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Page {
+        page: u32,
+        verbose: bool,
+    }
+
+    #[tokio::test]
+    async fn query_extractor_coerces_numeric_and_bool_fields() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.args.insert("page".to_string(), "2".to_string());
+        request.args.insert("verbose".to_string(), "true".to_string());
+
+        let Query(page) = Query::<Page>::from_request(&request).unwrap();
+        assert_eq!(page, Page { page: 2, verbose: true });
+    }
+
+    #[tokio::test]
+    async fn query_extractor_rejects_non_numeric_field() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.args.insert("page".to_string(), "not-a-number".to_string());
+        request.args.insert("verbose".to_string(), "true".to_string());
+
+        assert!(Query::<Page>::from_request(&request).is_err());
+    }
+
+    #[tokio::test]
+    async fn path_params_extractor_coerces_numeric_fields() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.params.insert("page".to_string(), "7".to_string());
+        request.params.insert("verbose".to_string(), "false".to_string());
+
+        let PathParams(page) = PathParams::<Page>::from_request(&request).unwrap();
+        assert_eq!(page, Page { page: 7, verbose: false });
+    }
+
+    #[tokio::test]
+    async fn json_extractor_deserializes_request_body() {
+        let stream = dummy_stream().await;
+        let mut request = make_request(&stream);
+        request.body = br#"{"page": 3, "verbose": true}"#.to_vec();
+
+        let Json(page) = Json::<Page>::from_request(&request).unwrap();
+        assert_eq!(page, Page { page: 3, verbose: true });
+    }
+
+    #[test]
+    fn response_builder_sets_status_header_and_cookie_overrides() {
+        let built = Response::TEXT("hi".to_string())
+            .builder()
+            .status(201)
+            .header("X-Extra", "1")
+            .cookie(Cookie::new("session", "abc"))
+            .build();
+
+        let Response::CUSTOM(inner, overrides) = built else {
+            panic!("builder() must produce Response::CUSTOM");
+        };
+        assert!(matches!(*inner, Response::TEXT(text) if text == "hi"));
+        assert_eq!(overrides.status, Some(201));
+        assert_eq!(overrides.headers.get("X-Extra"), Some(&"1".to_string()));
+        assert_eq!(overrides.cookies.len(), 1);
+    }
+
+    #[test]
+    fn response_builder_remove_header_undoes_an_earlier_header_call() {
+        let built = Response::TEXT("hi".to_string())
+            .builder()
+            .header("X-Extra", "1")
+            .remove_header("X-Extra")
+            .build();
+
+        let Response::CUSTOM(_, overrides) = built else {
+            panic!("builder() must produce Response::CUSTOM");
+        };
+        assert!(!overrides.headers.contains_key("X-Extra"));
+    }
+
+    #[test]
+    fn response_builder_reuses_overrides_instead_of_nesting_custom() {
+        let once = Response::TEXT("hi".to_string()).builder().status(201).build();
+        let twice = once.builder().header("X-Extra", "1").build();
+
+        let Response::CUSTOM(inner, overrides) = twice else {
+            panic!("builder() must produce Response::CUSTOM");
+        };
+        // Not Response::CUSTOM(Box::new(Response::CUSTOM(..)), ..) — the
+        // second builder() call should fold into the first's overrides.
+        assert!(matches!(*inner, Response::TEXT(text) if text == "hi"));
+        assert_eq!(overrides.status, Some(201));
+        assert_eq!(overrides.headers.get("X-Extra"), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn cookie_to_header_value_includes_every_set_attribute() {
+        let cookie = Cookie::new("session", "abc")
+            .path("/")
+            .max_age(3600)
+            .same_site("Strict")
+            .http_only(true);
+        assert_eq!(
+            cookie.to_header_value(),
+            "session=abc; Path=/; Max-Age=3600; SameSite=Strict; HttpOnly"
+        );
+    }
+
+    #[test]
+    fn cookie_to_header_value_omits_unset_attributes() {
+        let cookie = Cookie::new("session", "abc");
+        assert_eq!(cookie.to_header_value(), "session=abc");
+    }
+
+    #[test]
+    fn reason_phrase_known_and_unknown_codes() {
+        assert_eq!(reason_phrase(200), "OK");
+        assert_eq!(reason_phrase(404), "Not Found");
+        assert_eq!(reason_phrase(999), "");
+    }
+
+    async fn write_raw_response_bytes(
+        status: u16,
+        headers: HashMap<String, String>,
+        cookies: Vec<Cookie>,
+        body: Vec<u8>,
+    ) -> String {
+        let (mut client, mut server) = connected_pair().await;
+        // The server side is dropped (closing the socket) once this task
+        // ends, which is what lets the client's read_to_end below observe EOF.
+        tokio::spawn(async move {
+            WebService::<'_, ()>::write_raw_response(&mut server, status, headers, &cookies, &body).await;
+        })
+        .await
+        .unwrap();
+
+        let mut out = Vec::new();
+        let _ = client.read_to_end(&mut out).await;
+        String::from_utf8_lossy(&out).to_string()
+    }
+
+    #[tokio::test]
+    async fn write_raw_response_always_sets_content_length_for_ok_with_empty_body() {
+        let out = write_raw_response_bytes(200, HashMap::new(), Vec::new(), Vec::new()).await;
+        assert!(out.contains("Content-Length: 0"), "response was: {out:?}");
+    }
+
+    #[tokio::test]
+    async fn write_raw_response_omits_content_length_for_204() {
+        let out = write_raw_response_bytes(204, HashMap::new(), Vec::new(), Vec::new()).await;
+        assert!(!out.contains("Content-Length"), "response was: {out:?}");
+    }
+
+    #[tokio::test]
+    async fn write_raw_response_omits_content_length_for_304() {
+        let out = write_raw_response_bytes(304, HashMap::new(), Vec::new(), Vec::new()).await;
+        assert!(!out.contains("Content-Length"), "response was: {out:?}");
+    }
+
+    #[tokio::test]
+    async fn write_raw_response_sets_content_length_to_body_len() {
+        let out = write_raw_response_bytes(200, HashMap::new(), Vec::new(), b"hello".to_vec()).await;
+        assert!(out.contains("Content-Length: 5"), "response was: {out:?}");
+        assert!(out.ends_with("hello"));
+    }
 }